@@ -0,0 +1,154 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A small pool of fixed-size buffers, recycled between callers instead of
+//! freshly allocated on every read, for buffered reader/writer
+//! implementations serving many short-lived connections.
+
+use container::Container;
+use iterator::Iterator;
+use option::{Option, Some, None};
+use unstable::sync::{Exclusive, exclusive};
+use vec;
+use vec::{OwnedVector, MutableVector};
+
+/// Hands out `~[u8]` chunks of a fixed size and takes them back for reuse,
+/// so steady-state TCP serving doesn't churn the allocator with a fresh
+/// buffer per read. Bounded: once `capacity` buffers are checked in,
+/// further ones offered to `put` are simply dropped rather than growing
+/// the pool without limit. Safe to share between tasks by cloning, since
+/// the free list lives behind an `Exclusive`.
+pub struct BufferPool {
+    priv buf_size: uint,
+    priv capacity: uint,
+    priv free: Exclusive<~[~[u8]]>,
+}
+
+impl BufferPool {
+    /// Creates a pool that hands out buffers of `buf_size` bytes each,
+    /// holding on to at most `capacity` of them between uses.
+    pub fn new(buf_size: uint, capacity: uint) -> BufferPool {
+        BufferPool {
+            buf_size: buf_size,
+            capacity: capacity,
+            free: exclusive(~[]),
+        }
+    }
+
+    /// Duplicates this handle; every clone draws from and returns to the
+    /// same underlying buffers.
+    pub fn clone(&self) -> BufferPool {
+        BufferPool {
+            buf_size: self.buf_size,
+            capacity: self.capacity,
+            free: self.free.clone(),
+        }
+    }
+
+    /// Gets a zeroed buffer of this pool's fixed size, reusing a
+    /// previously `put` one where one is available.
+    pub fn get(&self) -> ~[u8] {
+        let recycled = unsafe {
+            do self.free.with |bufs| {
+                bufs.pop_opt()
+            }
+        };
+        match recycled {
+            Some(mut buf) => {
+                for buf.mut_iter().advance |byte| { *byte = 0; }
+                buf
+            }
+            None => vec::from_elem(self.buf_size, 0u8),
+        }
+    }
+
+    /// Returns a buffer for reuse. A buffer that isn't this pool's fixed
+    /// size, or offered once the pool already holds `capacity` of them,
+    /// is dropped instead of stored.
+    pub fn put(&self, buf: ~[u8]) {
+        if buf.len() != self.buf_size {
+            return;
+        }
+        unsafe {
+            do self.free.with |bufs| {
+                if bufs.len() < self.capacity {
+                    bufs.push(buf);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rt::test::*;
+    use comm::{GenericChan, GenericPort};
+    use comm;
+    use task;
+    use vec;
+    use iterator::Iterator;
+    use vec::ImmutableVector;
+
+    #[test]
+    fn get_recycles_a_put_buffer() {
+        let pool = BufferPool::new(16, 4);
+        let mut buf = pool.get();
+        assert_eq!(buf.len(), 16);
+        buf[0] = 42;
+        pool.put(buf);
+
+        // The next `get` must hand back a buffer of the same identity
+        // (there's only ever one in the pool at this point), zeroed
+        // rather than leaking the stale `42` from the previous borrower.
+        let buf2 = pool.get();
+        assert_eq!(buf2.len(), 16);
+        assert_eq!(buf2[0], 0);
+    }
+
+    #[test]
+    fn put_beyond_capacity_is_dropped_not_grown() {
+        let pool = BufferPool::new(8, 1);
+        pool.put(vec::from_elem(8, 1u8));
+        pool.put(vec::from_elem(8, 2u8));
+
+        let first = pool.get();
+        let second = pool.get();
+        // Only one of the two `put`s could have been kept; the second
+        // `get` must fall back to a fresh, zeroed buffer rather than
+        // returning a second stored one that was never allowed in.
+        assert!(first == vec::from_elem(8, 1u8) || first == vec::from_elem(8, 2u8));
+        assert_eq!(second, vec::from_elem(8, 0u8));
+    }
+
+    #[test]
+    fn shared_across_tasks_reuses_buffers() {
+        do run_in_newsched_task {
+            let pool = BufferPool::new(32, 8);
+            let (port, chan) = comm::stream();
+            let chan = comm::SharedChan::new(chan);
+
+            for 20.times {
+                let pool = pool.clone();
+                let chan = chan.clone();
+                do task::spawn {
+                    let mut buf = pool.get();
+                    assert_eq!(buf.len(), 32);
+                    assert!(buf.iter().all(|&b| b == 0));
+                    buf[0] = 7;
+                    pool.put(buf);
+                    chan.send(());
+                }
+            }
+
+            for 20.times { port.recv(); }
+        }
+    }
+}
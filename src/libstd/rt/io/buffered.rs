@@ -0,0 +1,500 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Buffering wrappers for `Reader`s and `Writer`s, suited to line-oriented
+//! or field-oriented protocols (SMTP, HTTP headers, ...) where a naive
+//! `Reader`/`Writer` would otherwise take one syscall per byte or per
+//! small field.
+
+use cast;
+use cmp;
+use container::{Container, Mutable};
+use iterator::Iterator;
+use option::{Option, Some, None};
+use rt::io::{Reader, Writer, DEFAULT_BUF_SIZE};
+use str;
+use vec;
+use vec::{ImmutableVector, MutableVector, OwnedCopyableVector, OwnedVector, Vector};
+
+/// Upper bound the adaptive refill chunk in `BufferedReader` is allowed to
+/// grow to, so a very fast stream can't make it balloon without limit.
+static MAX_CHUNK_SIZE: uint = DEFAULT_BUF_SIZE * 8;
+
+/// Lower bound the adaptive refill chunk in `BufferedReader` is allowed to
+/// shrink to.
+static MIN_CHUNK_SIZE: uint = 512;
+
+/// Wraps a `Reader`, amortizing many small reads into occasional large
+/// ones by keeping an internal buffer that's refilled from the
+/// underlying reader once drained. The refill size adapts to the
+/// connection: it grows toward `MAX_CHUNK_SIZE` while reads keep filling
+/// the buffer completely (a fast stream, worth fewer/bigger syscalls) and
+/// shrinks toward `MIN_CHUNK_SIZE` when reads come back mostly empty (a
+/// slow or interactive stream, not worth buffering much of).
+pub struct BufferedReader<R> {
+    priv inner: R,
+    priv buf: ~[u8],
+    priv pos: uint,
+    priv cap: uint,
+    priv chunk: uint,
+}
+
+impl<R: Reader> BufferedReader<R> {
+    /// Creates a new `BufferedReader` with a default buffer capacity.
+    pub fn new(inner: R) -> BufferedReader<R> {
+        BufferedReader::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Creates a new `BufferedReader` with the given initial buffer
+    /// capacity. This is only a starting point: the buffer's actual size
+    /// adapts to observed throughput from there. See the type's own docs.
+    pub fn with_capacity(cap: uint, inner: R) -> BufferedReader<R> {
+        BufferedReader {
+            inner: inner,
+            buf: vec::from_elem(cap, 0u8),
+            pos: 0,
+            cap: 0,
+            chunk: cap,
+        }
+    }
+
+    /// The refill chunk size the adaptive logic has currently settled on.
+    /// Exposed for testing; not otherwise useful to a caller.
+    pub fn chunk_size(&self) -> uint {
+        self.chunk
+    }
+
+    /// Refills the buffer from the underlying reader. Only valid to call
+    /// once the buffer has been fully drained. Returns `false` on EOF.
+    fn fill(&mut self) -> bool {
+        assert!(self.pos == self.cap);
+        if self.buf.len() != self.chunk {
+            self.buf = vec::from_elem(self.chunk, 0u8);
+        }
+        let len = self.buf.len();
+        match self.inner.read(self.buf.mut_slice(0, len)) {
+            Some(n) => {
+                self.pos = 0;
+                self.cap = n;
+                self.adjust_chunk(n, len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Grows or shrinks `chunk` based on how much of the last requested
+    /// refill actually came back.
+    fn adjust_chunk(&mut self, filled: uint, requested: uint) {
+        if filled == requested {
+            // The stream gave us everything we asked for; it can probably
+            // sustain bigger reads, so ask for more next time.
+            self.chunk = cmp::min(self.chunk * 2, MAX_CHUNK_SIZE);
+        } else if filled > 0 && filled * 4 < requested {
+            // We got back much less than we asked for; buffering this
+            // much is wasted memory for a stream this slow.
+            self.chunk = cmp::max(self.chunk / 2, cmp::min(self.chunk, MIN_CHUNK_SIZE));
+        }
+    }
+
+    /// Reads a single byte. Returns `None` on EOF.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.pos == self.cap && !self.fill() {
+            return None;
+        }
+        let byte = self.buf[self.pos];
+        self.pos += 1;
+        Some(byte)
+    }
+
+    /// Reads bytes up to and including `delim`. If EOF is reached first,
+    /// returns whatever was read before it, or `None` if nothing was read
+    /// at all. The returned vector grows to fit however long the run
+    /// turns out to be, so very long lines are not a problem.
+    pub fn read_until(&mut self, delim: u8) -> Option<~[u8]> {
+        let mut out = ~[];
+        loop {
+            match self.read_byte() {
+                Some(b) => {
+                    out.push(b);
+                    if b == delim { return Some(out); }
+                }
+                None => {
+                    return if out.is_empty() { None } else { Some(out) };
+                }
+            }
+        }
+    }
+
+    /// Reads a `\n`-terminated line, not including the `\n`. As with
+    /// `read_until`, a line left unterminated by EOF is still returned;
+    /// only a `None` return means no more data was available at all.
+    pub fn read_line(&mut self) -> Option<~str> {
+        match self.read_until('\n' as u8) {
+            Some(mut bytes) => {
+                if !bytes.is_empty() && bytes[bytes.len() - 1] == '\n' as u8 {
+                    bytes.pop();
+                }
+                Some(str::from_bytes_owned(bytes))
+            }
+            None => None,
+        }
+    }
+
+    /// Returns an iterator over `\n`-terminated lines, each yielded as a
+    /// `~str` with the trailing `\n` stripped, as `read_line` does. A
+    /// trailing line with no final `\n` is still yielded before the
+    /// iterator ends. Invalid UTF-8 raises the same `str::not_utf8`
+    /// condition that `read_line` does; there is no lossy or skipping
+    /// mode.
+    pub fn lines<'r>(&'r mut self) -> Lines<'r, R> {
+        Lines { reader: self }
+    }
+
+    /// Returns an iterator yielding one `u8` at a time, until EOF. Backed
+    /// by `read_byte`, so most bytes come out of the already-filled buffer
+    /// instead of costing a syscall each; only a buffer refill ever touches
+    /// the underlying reader. An error mid-stream raises the same
+    /// condition `read_byte` would, and ends the iterator early.
+    pub fn bytes<'r>(&'r mut self) -> Bytes<'r, R> {
+        Bytes { reader: self }
+    }
+}
+
+/// Iterator over the lines of a `BufferedReader`, created by `lines()`.
+pub struct Lines<'self, R> {
+    priv reader: &'self mut BufferedReader<R>,
+}
+
+impl<'self, R: Reader> Iterator<~str> for Lines<'self, R> {
+    fn next(&mut self) -> Option<~str> {
+        self.reader.read_line()
+    }
+}
+
+/// Iterator over the bytes of a `BufferedReader`, created by `bytes()`.
+pub struct Bytes<'self, R> {
+    priv reader: &'self mut BufferedReader<R>,
+}
+
+impl<'self, R: Reader> Iterator<u8> for Bytes<'self, R> {
+    fn next(&mut self) -> Option<u8> {
+        self.reader.read_byte()
+    }
+}
+
+impl<R: Reader> Reader for BufferedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Option<uint> {
+        if self.pos == self.cap {
+            // A read at least as big as our buffer can't benefit from
+            // going through it, so pass it straight to the inner reader.
+            if buf.len() >= self.buf.len() {
+                return self.inner.read(buf);
+            }
+            if !self.fill() { return None; }
+        }
+
+        let n = cmp::min(buf.len(), self.cap - self.pos);
+        vec::bytes::copy_memory(buf, self.buf.slice(self.pos, self.pos + n), n);
+        self.pos += n;
+        Some(n)
+    }
+
+    fn eof(&mut self) -> bool {
+        self.pos == self.cap && self.inner.eof()
+    }
+}
+
+/// Wraps a `Writer`, batching many small writes into occasional large
+/// ones by accumulating them into an internal buffer that's flushed to
+/// the underlying writer once full, on an explicit `flush()`, or when
+/// the `BufferedWriter` is dropped.
+pub struct BufferedWriter<W> {
+    priv inner: W,
+    priv buf: ~[u8],
+    /// Whole buffers queued by `write_frame`, kept apart from `buf` so
+    /// `flush_vectored` can hand them to the underlying writer as-is.
+    priv frames: ~[~[u8]],
+}
+
+impl<W: Writer> BufferedWriter<W> {
+    /// Creates a new `BufferedWriter` with a default buffer capacity.
+    pub fn new(inner: W) -> BufferedWriter<W> {
+        BufferedWriter::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    /// Creates a new `BufferedWriter` with the given buffer capacity.
+    pub fn with_capacity(cap: uint, inner: W) -> BufferedWriter<W> {
+        BufferedWriter {
+            inner: inner,
+            buf: vec::with_capacity(cap),
+            frames: ~[],
+        }
+    }
+}
+
+/// A `Writer` that can gather several buffers into one underlying write.
+/// Implemented for `TcpStream`, whose `write_vectored` reaches an actual
+/// `writev`-style gathered write instead of copying its inputs together.
+pub trait VectoredWriter: Writer {
+    fn write_vectored(&mut self, bufs: &[&[u8]]) -> Option<uint>;
+}
+
+impl<W: VectoredWriter> BufferedWriter<W> {
+    /// Queues `frame` to be sent by the next `flush_vectored` call as its
+    /// own segment of a gathered write, rather than being copied into the
+    /// contiguous buffer plain `write` calls share. Useful for protocols
+    /// that assemble many small, already-framed messages and want them
+    /// coalesced into as few underlying writes as possible.
+    ///
+    /// Unlike the plain buffer, queued frames are *not* flushed by the
+    /// `Drop` glue that saves a forgotten plain `flush`; call
+    /// `flush_vectored` explicitly before dropping.
+    pub fn write_frame(&mut self, frame: ~[u8]) {
+        self.frames.push(frame);
+    }
+
+    /// As `flush`, but sends every frame queued by `write_frame` -- along
+    /// with anything already sitting in the plain buffer from ordinary
+    /// `write` calls -- as a single call to the underlying writer's
+    /// `write_vectored`, instead of first copying them all together into
+    /// one contiguous buffer the way `flush` would.
+    pub fn flush_vectored(&mut self) {
+        if self.frames.is_empty() {
+            self.flush();
+            return;
+        }
+
+        let mut bufs: ~[&[u8]] = ~[];
+        if !self.buf.is_empty() {
+            bufs.push(self.buf.as_slice());
+        }
+        for frame in self.frames.iter() {
+            bufs.push(frame.as_slice());
+        }
+        self.inner.write_vectored(bufs);
+
+        self.buf.clear();
+        self.frames.clear();
+        self.inner.flush();
+    }
+}
+
+impl<W: Writer> Writer for BufferedWriter<W> {
+    fn write(&mut self, buf: &[u8]) {
+        if self.buf.len() + buf.len() > self.buf.capacity() {
+            self.flush();
+        }
+
+        // A write that wouldn't fit in an empty buffer anyway can go
+        // straight to the inner writer instead of round-tripping through
+        // our buffer.
+        if buf.len() >= self.buf.capacity() {
+            self.inner.write_all(buf);
+        } else {
+            self.buf.push_all(buf);
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            self.inner.write_all(self.buf);
+            self.buf.clear();
+        }
+        self.inner.flush();
+    }
+}
+
+#[unsafe_destructor]
+impl<W: Writer> Drop for BufferedWriter<W> {
+    fn drop(&self) {
+        // Dropping a Writer with unflushed data would silently lose it.
+        // `flush` needs `&mut self`, which `drop` doesn't have; this is
+        // safe because nothing else can touch `self` during destruction.
+        unsafe { cast::transmute_mut(self).flush(); }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use iterator::IteratorUtil;
+    use rt::test::*;
+    use rt::io::extensions::ReaderUtil;
+    use rt::io::mem::MemReader;
+    use rt::io::net::tcp::{TcpListener, TcpStream};
+    use rt::io::{Listener, Reader, Writer};
+    use uint;
+    use vec;
+
+    #[test]
+    fn read_byte_and_read_until_from_mem() {
+        let mut r = BufferedReader::with_capacity(4, MemReader::new(~[1, 2, 3, 4, 5]));
+        assert_eq!(r.read_byte(), Some(1));
+        assert_eq!(r.read_until(4), Some(~[2, 3, 4]));
+        assert_eq!(r.read_until(9), Some(~[5]));
+        assert_eq!(r.read_until(9), None);
+    }
+
+    #[test]
+    fn adaptive_chunk_grows_on_fast_stream() {
+        // A stream that always has plenty of data on hand should push the
+        // refill chunk up on every fill, since each one comes back full.
+        let data = vec::from_elem(64 * 1024, 7u8);
+        let mut reader = BufferedReader::with_capacity(64, MemReader::new(data));
+        let initial = reader.chunk_size();
+        for 4000.times {
+            reader.read_byte();
+        }
+        assert!(reader.chunk_size() > initial);
+    }
+
+    #[test]
+    fn read_lines_over_tcp() {
+        do run_in_newsched_task {
+            let addr = next_test_ip4();
+            let lines = ~["hello\n", "buffered\n", "world\n"];
+
+            do spawntask_immediately {
+                let listener = TcpListener::bind(addr).expect("bind failed");
+                let mut acceptor = listener.listen();
+                let mut stream = acceptor.accept();
+                for lines.iter().advance |line| {
+                    stream.write(line.as_bytes());
+                }
+                // Drop `stream` here to close it, so the reading end
+                // sees EOF after the last line.
+            }
+
+            do spawntask_immediately {
+                let stream = TcpStream::connect(addr).expect("connect failed");
+                let mut reader = BufferedReader::new(stream);
+                assert_eq!(reader.read_line(), Some(~"hello"));
+                assert_eq!(reader.read_line(), Some(~"buffered"));
+                assert_eq!(reader.read_line(), Some(~"world"));
+                assert_eq!(reader.read_line(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn lines_iterator_over_tcp() {
+        do run_in_newsched_task {
+            let addr = next_test_ip4();
+            let chunks = ~["one\n", "two\n", "three\n", "trailing"];
+
+            do spawntask_immediately {
+                let listener = TcpListener::bind(addr).expect("bind failed");
+                let mut acceptor = listener.listen();
+                let mut stream = acceptor.accept();
+                for chunks.iter().advance |chunk| {
+                    stream.write(chunk.as_bytes());
+                }
+                // Drop `stream` here to close it, so the reading end sees
+                // EOF after the unterminated trailing line.
+            }
+
+            do spawntask_immediately {
+                let stream = TcpStream::connect(addr).expect("connect failed");
+                let mut reader = BufferedReader::new(stream);
+                let lines: ~[~str] = reader.lines().collect();
+                assert_eq!(lines, ~[~"one", ~"two", ~"three", ~"trailing"]);
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_iterator_over_tcp() {
+        do run_in_newsched_task {
+            let addr = next_test_ip4();
+            let msg = "hello";
+
+            do spawntask_immediately {
+                let listener = TcpListener::bind(addr).expect("bind failed");
+                let mut acceptor = listener.listen();
+                let mut stream = acceptor.accept();
+                stream.write(msg.as_bytes());
+                // Drop `stream` here to close it, so the reading end sees
+                // EOF after the message.
+            }
+
+            do spawntask_immediately {
+                let stream = TcpStream::connect(addr).expect("connect failed");
+                let mut reader = BufferedReader::new(stream);
+                let bytes: ~[u8] = reader.bytes().collect();
+                assert_eq!(bytes, msg.as_bytes().to_owned());
+            }
+        }
+    }
+
+    struct CountingVectoredWriter {
+        calls: uint,
+        received: ~[u8],
+    }
+
+    impl Writer for CountingVectoredWriter {
+        fn write(&mut self, buf: &[u8]) { self.received.push_all(buf); }
+        fn flush(&mut self) {}
+    }
+
+    impl super::VectoredWriter for CountingVectoredWriter {
+        fn write_vectored(&mut self, bufs: &[&[u8]]) -> Option<uint> {
+            self.calls += 1;
+            let mut total = 0;
+            for buf in bufs.iter() {
+                self.received.push_all(*buf);
+                total += buf.len();
+            }
+            Some(total)
+        }
+    }
+
+    #[test]
+    fn flush_vectored_coalesces_frames_into_one_write() {
+        let frames = 32;
+        let mut writer = BufferedWriter::new(CountingVectoredWriter { calls: 0, received: ~[] });
+        for uint::range(0, frames) |i| {
+            writer.write_frame(~[i as u8]);
+        }
+        writer.flush_vectored();
+
+        assert_eq!(writer.inner.calls, 1);
+        let expected: ~[u8] = vec::from_fn(frames, |i| i as u8);
+        assert_eq!(writer.inner.received, expected);
+    }
+
+    #[test]
+    fn buffered_writer_batches_and_flushes() {
+        do run_in_newsched_task {
+            let addr = next_test_ip4();
+            let chunks = 128;
+
+            do spawntask_immediately {
+                let listener = TcpListener::bind(addr).expect("bind failed");
+                let mut acceptor = listener.listen();
+                let mut stream = acceptor.accept();
+                let received = stream.read_to_end();
+                let expected: ~[u8] = vec::from_fn(chunks, |i| i as u8);
+                assert_eq!(received, expected);
+            }
+
+            do spawntask_immediately {
+                let stream = TcpStream::connect(addr).expect("connect failed");
+                let mut writer = BufferedWriter::with_capacity(16, stream);
+                for uint::range(0, chunks) |i| {
+                    writer.write([i as u8]);
+                }
+                writer.flush();
+                // Drop `writer` here, closing the stream so the reading
+                // end sees EOF after the flushed bytes.
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A cross-task cancellation flag for blocking I/O operations that poll
+//! for it, so a server can ask a blocked accept loop (or, backend
+//! permitting, a blocked read) to give up and return cleanly instead of
+//! killing the task outright.
+
+use unstable::sync::{Exclusive, exclusive};
+
+/// A handle used to request cancellation of a blocking operation from
+/// another task. Cheap to `clone`; every clone shares the same
+/// underlying flag, so triggering any of them cancels whatever is
+/// polling any other.
+pub struct CancelToken {
+    priv flag: Exclusive<bool>,
+}
+
+impl CancelToken {
+    /// Creates a fresh, not-yet-cancelled token.
+    pub fn new() -> CancelToken {
+        CancelToken { flag: exclusive(false) }
+    }
+
+    /// Duplicates this token; every clone shares the same underlying
+    /// flag.
+    pub fn clone(&self) -> CancelToken {
+        CancelToken { flag: self.flag.clone() }
+    }
+
+    /// Requests cancellation. Idempotent, and has no effect if nothing
+    /// ends up polling this token before the operation it was meant to
+    /// interrupt finishes on its own.
+    pub fn cancel(&self) {
+        unsafe { do self.flag.with |cancelled| { *cancelled = true; } }
+    }
+
+    /// Whether `cancel` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        unsafe { do self.flag.with |cancelled| { *cancelled } }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cancel_is_visible_through_a_clone() {
+        let token = CancelToken::new();
+        let other = token.clone();
+        assert!(!other.is_cancelled());
+        token.cancel();
+        assert!(other.is_cancelled());
+    }
+}
@@ -594,10 +594,20 @@ mod test {
     use super::ReaderUtil;
     use option::{Some, None};
     use cell::Cell;
-    use rt::io::mem::MemReader;
+    use rt::io::mem::{MemReader, MemWriter};
     use rt::io::mock::MockReader;
+    use rt::io::{Reader, Writer, Decorator};
     use rt::io::{read_error, placeholder_error};
 
+    #[test]
+    fn write_all() {
+        let mut writer = MemWriter::new();
+        writer.write_all([1, 2, 3]);
+        writer.write_all([]);
+        writer.write_all([4, 5]);
+        assert_eq!(writer.inner(), ~[1, 2, 3, 4, 5]);
+    }
+
     #[test]
     fn read_byte() {
         let mut reader = MemReader::new(~[10]);
@@ -0,0 +1,107 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use cmp;
+use option::{Option, Some, None};
+use rt::io::Reader;
+
+/// Wraps a `Reader`, refusing to read past a fixed byte budget. Useful
+/// for framed protocols where a length prefix says a message is exactly
+/// `n` bytes long, and reading past that would consume the start of the
+/// next frame.
+pub struct LimitReader<R> {
+    priv inner: R,
+    priv limit: uint,
+}
+
+impl<R: Reader> LimitReader<R> {
+    /// Creates a new `LimitReader` that will read at most `limit` bytes
+    /// from `inner` before reporting EOF.
+    pub fn new(inner: R, limit: uint) -> LimitReader<R> {
+        LimitReader { inner: inner, limit: limit }
+    }
+
+    /// The number of bytes still readable before the limit is reached.
+    pub fn remaining(&self) -> uint {
+        self.limit
+    }
+
+    /// Unwraps this `LimitReader`, returning the underlying reader. Any
+    /// bytes past the limit that were never read remain available on it.
+    pub fn unwrap(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Reader> Reader for LimitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Option<uint> {
+        if self.limit == 0 {
+            return None;
+        }
+
+        let len = cmp::min(buf.len(), self.limit);
+        match self.inner.read(buf.mut_slice(0, len)) {
+            Some(n) => {
+                self.limit -= n;
+                Some(n)
+            }
+            None => None,
+        }
+    }
+
+    fn eof(&mut self) -> bool {
+        self.limit == 0 || self.inner.eof()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rt::io::mem::MemReader;
+    use rt::io::extensions::ReaderUtil;
+    use rt::io::net::tcp::{TcpListener, TcpStream};
+    use rt::io::{Listener, Reader, Writer};
+    use rt::test::*;
+
+    #[test]
+    fn read_exactly_n_bytes_leaves_the_rest_for_later() {
+        let mem = MemReader::new(~[1, 2, 3, 4, 5]);
+        let mut limited = LimitReader::new(mem, 3);
+        assert_eq!(limited.read_to_end(), ~[1, 2, 3]);
+        assert!(limited.eof());
+
+        let mut rest = limited.unwrap();
+        assert_eq!(rest.read_to_end(), ~[4, 5]);
+    }
+
+    #[test]
+    fn read_exactly_n_bytes_off_a_stream_with_more() {
+        do run_in_newsched_task {
+            let addr = next_test_ip4();
+
+            do spawntask_immediately {
+                let listener = TcpListener::bind(addr).expect("bind failed");
+                let mut acceptor = listener.listen();
+                let mut stream = acceptor.accept();
+                stream.write([1, 2, 3, 4, 5]);
+            }
+
+            do spawntask_immediately {
+                let stream = TcpStream::connect(addr).expect("connect failed");
+                let mut limited = LimitReader::new(stream, 3);
+                assert_eq!(limited.read_to_end(), ~[1, 2, 3]);
+                assert!(limited.eof());
+
+                let mut rest = limited.unwrap();
+                assert_eq!(rest.read_to_end(), ~[4, 5]);
+            }
+        }
+    }
+}
@@ -219,4 +219,18 @@ mod test {
         assert_eq!(reader.read(buf), None);
         assert!(reader.eof());
     }
+
+    #[test]
+    fn test_mem_writer_reader_roundtrip() {
+        let mut writer = MemWriter::new();
+        writer.write([1, 2, 3]);
+        writer.write([4, 5]);
+
+        let mut reader = MemReader::new(writer.inner());
+        let mut buf = [0, ..5];
+        assert_eq!(reader.read(buf), Some(5));
+        assert_eq!(buf, [1, 2, 3, 4, 5]);
+        assert!(reader.eof());
+        assert_eq!(reader.read(buf), None);
+    }
 }
@@ -253,10 +253,16 @@ pub use self::stdio::println;
 
 pub use self::file::FileStream;
 pub use self::timer::Timer;
-pub use self::net::ip::IpAddr;
+pub use self::net::ip::{IpAddr, SocketAddr};
 pub use self::net::tcp::TcpListener;
 pub use self::net::tcp::TcpStream;
 pub use self::net::udp::UdpStream;
+pub use self::buffered::{BufferedReader, BufferedWriter};
+pub use self::buffer_pool::BufferPool;
+pub use self::limit::LimitReader;
+pub use self::tee::{TeeReader, BroadcastWriter};
+pub use self::cancel::CancelToken;
+pub use self::select::Selector;
 
 // Some extension traits that all Readers and Writers get.
 pub use self::extensions::ReaderUtil;
@@ -279,6 +285,27 @@ pub mod net {
 /// Readers and Writers for memory buffers and strings.
 pub mod mem;
 
+/// Buffering wrappers for slow or syscall-heavy Readers and Writers.
+pub mod buffered;
+
+/// A pool of recycled fixed-size buffers, for buffered Readers/Writers
+/// that would otherwise allocate fresh ones on every use.
+pub mod buffer_pool;
+
+/// A `Reader` adapter that enforces a fixed byte budget, for
+/// length-prefixed framing.
+pub mod limit;
+
+/// Duplicating a `Reader`'s bytes into a side `Writer`, and a `Writer`
+/// that fans out to several sinks at once.
+pub mod tee;
+
+/// A cross-task cancellation flag for blocking I/O operations.
+pub mod cancel;
+
+/// Waiting on several `TcpStream`s at once without a task per connection.
+pub mod select;
+
 /// Non-blocking access to stdin, stdout, stderr
 pub mod stdio;
 
@@ -331,7 +358,11 @@ pub static DEFAULT_BUF_SIZE: uint = 1024 * 64;
 pub struct IoError {
     kind: IoErrorKind,
     desc: &'static str,
-    detail: Option<~str>
+    detail: Option<~str>,
+    /// The OS-level error code that produced this `IoError`, when the
+    /// failure originated from a system call. `None` for errors raised
+    /// entirely in userspace (e.g. `EndOfFile`).
+    errno: Option<int>,
 }
 
 #[deriving(Eq)]
@@ -345,7 +376,35 @@ pub enum IoErrorKind {
     Closed,
     ConnectionRefused,
     ConnectionReset,
-    BrokenPipe
+    BrokenPipe,
+    /// An established operation (a read, a write, an accept) exceeded its
+    /// deadline. Unlike `ConnectionTimedOut`, the connection itself is
+    /// otherwise fine; retrying the same operation is reasonable.
+    TimedOut,
+    /// A connection attempt's three-way handshake never completed within
+    /// its deadline, e.g. because the peer is unreachable or behind a
+    /// firewall silently dropping packets. Distinct from `TimedOut` so a
+    /// caller can tell "never connected" apart from "connected, then a
+    /// later operation stalled".
+    ConnectionTimedOut,
+    /// The operation was interrupted by a signal (`EINTR`) before it could
+    /// do any work. Always safe to retry: nothing was consumed or sent.
+    Interrupted,
+    /// The call failed because a per-process or system-wide resource limit
+    /// was hit (e.g. `EMFILE`/`ENFILE` while accepting a connection), not
+    /// because anything is wrong with the connection itself. Retrying
+    /// immediately will likely fail again; back off first.
+    ResourceExhausted,
+    /// A blocking operation was asked to give up early via a
+    /// `CancelToken`, rather than failing on its own. Unlike
+    /// `Interrupted`, retrying is the caller's choice, not automatic --
+    /// this is a deliberate request to stop, not a spurious wakeup.
+    Cancelled,
+    /// A non-blocking poll (e.g. `try_accept`) found nothing ready and
+    /// returned immediately rather than parking the task. Not a real
+    /// error: the caller should simply try again later, typically after
+    /// its own select loop reports readiness.
+    WouldBlock
 }
 
 // XXX: Can't put doc comments on macros
@@ -387,6 +446,32 @@ pub trait Reader {
     /// Is it actually possible for 0 bytes to be read successfully?
     fn read(&mut self, buf: &mut [u8]) -> Option<uint>;
 
+    /// Reads exactly `buf.len()` bytes, looping over `read` as many times
+    /// as it takes to fill the buffer, e.g. for a fixed-size protocol
+    /// header that may arrive split across several packets.
+    ///
+    /// # Failure
+    ///
+    /// Raises the same conditions as `read`. Additionally raises
+    /// `read_error` with kind `EndOfFile`, and returns `None`, if the
+    /// stream ends before `buf` is completely filled. Unlike `read`
+    /// itself, a short read here is always indistinguishable from
+    /// outright failure to the caller, since there's no way to also
+    /// report how many of `buf`'s bytes (if any) were actually filled in.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Option<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(buf.mut_slice(filled, buf.len())) {
+                Some(n) => filled += n,
+                None => {
+                    read_error::cond.raise(standard_error(EndOfFile));
+                    return None;
+                }
+            }
+        }
+        Some(())
+    }
+
     /// Return whether the Reader has reached the end of the stream.
     ///
     /// # Example
@@ -412,6 +497,22 @@ pub trait Writer {
 
     /// Flush output
     fn flush(&mut self);
+
+    /// Write the entire contents of `buf`
+    ///
+    /// This method exists for callers who don't want to reason about
+    /// whether `write` accepted the whole buffer; the default
+    /// implementation simply forwards to `write` since implementations
+    /// of `write` in this library are expected to write the whole
+    /// buffer or raise `io_error`, but a `Writer` whose `write` can
+    /// return early should override this method.
+    ///
+    /// # Failure
+    ///
+    /// Raises the same conditions as `write`
+    fn write_all(&mut self, buf: &[u8]) {
+        self.write(buf);
+    }
 }
 
 pub trait Stream: Reader + Writer { }
@@ -485,14 +586,16 @@ pub fn standard_error(kind: IoErrorKind) -> IoError {
             IoError {
                 kind: PreviousIoError,
                 desc: "Failing due to a previous I/O error",
-                detail: None
+                detail: None,
+                errno: None
             }
         }
         EndOfFile => {
             IoError {
                 kind: EndOfFile,
                 desc: "End of file",
-                detail: None
+                detail: None,
+                errno: None
             }
         }
         _ => fail!()
@@ -503,6 +606,7 @@ pub fn placeholder_error() -> IoError {
     IoError {
         kind: OtherIoError,
         desc: "Placeholder error. You shouldn't be seeing this",
-        detail: None
+        detail: None,
+        errno: None
     }
 }
\ No newline at end of file
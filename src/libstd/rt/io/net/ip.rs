@@ -8,10 +8,244 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use container::Container;
+use from_str::FromStr;
+use iterator::{Iterator, IteratorUtil};
+use num::FromStrRadix;
+use option::{Option, Some, None};
+use str::{OwnedStr, StrSlice};
+use to_str::ToStr;
+use vec::OwnedVector;
+use uint;
+
 type Port = u16;
 
 #[deriving(Eq, TotalEq)]
 pub enum IpAddr {
-    Ipv4(u8, u8, u8, u8, Port),
-    Ipv6(u16, u16, u16, u16, u16, u16, u16, u16, Port)
+    Ipv4(u8, u8, u8, u8),
+    /// The last field is the scope/zone id needed to disambiguate a
+    /// link-local address (e.g. `fe80::1%2`) across multiple interfaces;
+    /// `0` means "no scope id given". Only numeric zone ids round-trip
+    /// through `FromStr`/`ToStr` -- resolving an interface *name* like
+    /// `%eth0` to its index would need an `if_nametoindex`-style syscall
+    /// this tree doesn't currently wrap.
+    Ipv6(u16, u16, u16, u16, u16, u16, u16, u16, u32)
+}
+
+/// A host address paired with the transport-layer port it's reachable on.
+/// `IpAddr` alone only names a host; binding or connecting a socket also
+/// needs a port, which is what `SocketAddr` adds.
+#[deriving(Eq, TotalEq)]
+pub struct SocketAddr {
+    ip: IpAddr,
+    port: Port
+}
+
+impl FromStr for IpAddr {
+    fn from_str(s: &str) -> Option<IpAddr> {
+        match parse_ipv4(s) {
+            Some(addr) => Some(addr),
+            None => parse_ipv6(s),
+        }
+    }
+}
+
+fn parse_ipv4(s: &str) -> Option<IpAddr> {
+    let parts: ~[&str] = s.split_iter('.').collect();
+    match parts {
+        [a, b, c, d] => {
+            match (FromStr::from_str::<u8>(a), FromStr::from_str::<u8>(b),
+                   FromStr::from_str::<u8>(c), FromStr::from_str::<u8>(d)) {
+                (Some(a), Some(b), Some(c), Some(d)) => Some(Ipv4(a, b, c, d)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn parse_ipv6(s: &str) -> Option<IpAddr> {
+    // A trailing `%zone` names the scope id for a link-local address; peel
+    // it off before parsing the address proper. Only a numeric zone id is
+    // understood -- see the note on `Ipv6`.
+    let (s, scope_id) = match s.find('%') {
+        Some(i) => {
+            match FromStrRadix::from_str_radix(s.slice_from(i + 1), 10u) {
+                Some(id) => (s.slice_to(i), id),
+                None => return None,
+            }
+        }
+        None => (s, 0u32),
+    };
+
+    // A run of three or more colons is never valid, and rejecting it here
+    // keeps the "::"-splitting logic below from having to special-case it.
+    if s.contains(":::") { return None; }
+
+    let halves: ~[&str] = s.split_str_iter("::").collect();
+    let groups = match halves {
+        [whole] => parse_ipv6_groups(whole),
+        [left, right] => {
+            match (parse_ipv6_groups(left), parse_ipv6_groups(right)) {
+                (Some(l), Some(r)) if l.len() + r.len() <= 8 => {
+                    let mut groups = l;
+                    groups.grow(8 - groups.len() - r.len(), &0u16);
+                    groups.push_all(r);
+                    Some(groups)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    match groups {
+        Some(g) if g.len() == 8 =>
+            Some(Ipv6(g[0], g[1], g[2], g[3], g[4], g[5], g[6], g[7], scope_id)),
+        _ => None,
+    }
+}
+
+/// Parses a run of colon-separated hex groups, e.g. the `"1:2:3"` half of a
+/// `"1:2:3::4"` shorthand address. An empty string parses to no groups,
+/// which is what a `"::"` on either edge of the address expands to.
+fn parse_ipv6_groups(s: &str) -> Option<~[u16]> {
+    if s.is_empty() { return Some(~[]); }
+
+    let mut groups = ~[];
+    let mut ok = true;
+    for s.split_iter(':').advance |part| {
+        match FromStrRadix::from_str_radix(part, 16u) {
+            Some(group) => { groups.push(group); true }
+            None => { ok = false; false }
+        }
+    }
+    if ok { Some(groups) } else { None }
+}
+
+impl ToStr for IpAddr {
+    fn to_str(&self) -> ~str {
+        match *self {
+            Ipv4(a, b, c, d) =>
+                fmt!("%u.%u.%u.%u", a as uint, b as uint, c as uint, d as uint),
+            Ipv6(*) => ipv6_to_str(self),
+        }
+    }
+}
+
+fn ipv6_to_str(addr: &IpAddr) -> ~str {
+    let (groups, scope_id) = match *addr {
+        Ipv6(a, b, c, d, e, f, g, h, scope_id) => ([a, b, c, d, e, f, g, h], scope_id),
+        Ipv4(*) => fail!("ipv6_to_str called on an Ipv4 address"),
+    };
+
+    // Find the longest run of consecutive zero groups; a run of two or
+    // more collapses to "::" in the canonical textual form.
+    let mut best_start = 0;
+    let mut best_len = 0;
+    let mut cur_start = 0;
+    let mut cur_len = 0;
+    for uint::range(0, 8) |i| {
+        if groups[i] == 0 {
+            if cur_len == 0 { cur_start = i; }
+            cur_len += 1;
+            if cur_len > best_len {
+                best_start = cur_start;
+                best_len = cur_len;
+            }
+        } else {
+            cur_len = 0;
+        }
+    }
+
+    let mut out = ~"";
+    if best_len >= 2 {
+        for uint::range(0, best_start) |i| {
+            if i > 0 { out.push_str(":"); }
+            out.push_str(fmt!("%x", groups[i] as uint));
+        }
+        out.push_str("::");
+        for uint::range(best_start + best_len, 8) |i| {
+            if i > best_start + best_len { out.push_str(":"); }
+            out.push_str(fmt!("%x", groups[i] as uint));
+        }
+    } else {
+        for uint::range(0, 8) |i| {
+            if i > 0 { out.push_str(":"); }
+            out.push_str(fmt!("%x", groups[i] as uint));
+        }
+    }
+    if scope_id != 0 {
+        out.push_str(fmt!("%%%u", scope_id as uint));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn socket_addr_v4() {
+        let a = SocketAddr { ip: Ipv4(127, 0, 0, 1), port: 8080 };
+        assert_eq!(a.ip, Ipv4(127, 0, 0, 1));
+        assert_eq!(a.port, 8080);
+    }
+
+    #[test]
+    fn socket_addr_v6() {
+        let a = SocketAddr { ip: Ipv6(0, 0, 0, 0, 0, 0, 0, 1, 0), port: 8080 };
+        assert_eq!(a.ip, Ipv6(0, 0, 0, 0, 0, 0, 0, 1, 0));
+        assert_eq!(a.port, 8080);
+    }
+
+    #[test]
+    fn socket_addr_eq_considers_port() {
+        let a = SocketAddr { ip: Ipv4(10, 0, 0, 1), port: 80 };
+        let b = SocketAddr { ip: Ipv4(10, 0, 0, 1), port: 81 };
+        assert!(a != b);
+    }
+
+    #[test]
+    fn parses_and_displays_ipv4() {
+        for [~"0.0.0.0", ~"255.255.255.255", ~"127.0.0.1"].iter().advance |s| {
+            let addr: IpAddr = FromStr::from_str(*s).expect("should parse");
+            assert_eq!(addr.to_str(), s.to_str());
+        }
+    }
+
+    #[test]
+    fn parses_and_displays_ipv6() {
+        for [~"::", ~"::1"].iter().advance |s| {
+            let addr: IpAddr = FromStr::from_str(*s).expect("should parse");
+            assert_eq!(addr.to_str(), s.to_str());
+        }
+    }
+
+    #[test]
+    fn parses_fully_expanded_ipv6() {
+        let s = "2001:db8:0:0:0:0:0:1";
+        let addr: IpAddr = FromStr::from_str(s).expect("should parse");
+        assert_eq!(addr, Ipv6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1, 0));
+        // The canonical form compresses the run of zero groups.
+        assert_eq!(addr.to_str(), ~"2001:db8::1");
+    }
+
+    #[test]
+    fn parses_and_displays_link_local_with_scope_id() {
+        let s = "fe80::1%2";
+        let addr: IpAddr = FromStr::from_str(s).expect("should parse");
+        assert_eq!(addr, Ipv6(0xfe80, 0, 0, 0, 0, 0, 0, 1, 2));
+        assert_eq!(addr.to_str(), s.to_str());
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        let bad: Option<IpAddr> = FromStr::from_str("256.0.0.1");
+        assert!(bad.is_none());
+        let bad: Option<IpAddr> = FromStr::from_str(":::");
+        assert!(bad.is_none());
+        let bad: Option<IpAddr> = FromStr::from_str("::1%bogus");
+        assert!(bad.is_none());
+    }
 }
@@ -0,0 +1,108 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Waiting on several `TcpStream`s at once, so a task multiplexing many
+//! light connections doesn't need one task per connection.
+
+use container::Container;
+use iterator::Iterator;
+use option::{Option, Some, None};
+use rt::io::net::tcp::TcpStream;
+use rt::io::timer::Timer;
+use vec;
+use vec::{MutableVector, OwnedVector};
+
+/// How often `wait` re-checks the registered streams while none of them
+/// are readable yet.
+static POLL_INTERVAL_MS: u64 = 10;
+
+/// Waits until at least one of a fixed set of `TcpStream`s is readable,
+/// without spinning up a task per connection. Built on `TcpStream::readable`,
+/// polled at a short interval; not a true OS-level `select`/`epoll`; a
+/// backend that exposed real readiness notification could replace the
+/// polling loop below without changing this type's API.
+pub struct Selector<'self> {
+    priv streams: &'self mut [TcpStream],
+}
+
+impl<'self> Selector<'self> {
+    /// Registers `streams` for polling. Indices returned by `wait` refer
+    /// back into this same slice.
+    pub fn new(streams: &'self mut [TcpStream]) -> Selector<'self> {
+        Selector { streams: streams }
+    }
+
+    /// Blocks until at least one registered stream is readable, then
+    /// returns the indices of every stream that is, in ascending order.
+    pub fn wait(&mut self) -> ~[uint] {
+        loop {
+            let mut ready = vec::with_capacity(self.streams.len());
+            for (i, stream) in self.streams.mut_iter().enumerate() {
+                if stream.readable() {
+                    ready.push(i);
+                }
+            }
+            if !ready.is_empty() {
+                return ready;
+            }
+            match Timer::new() {
+                Some(timer) => timer.sleep(POLL_INTERVAL_MS),
+                // No timer available to back off with; rather than spin
+                // the CPU, give up and report nothing ready.
+                None => return ready,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rt::test::*;
+    use rt::io::net::tcp::{TcpListener, TcpStream};
+    use rt::io::{Listener, Reader, Writer};
+
+    // The uv backend doesn't implement readiness checks yet
+    // (RtioTcpStream::readable is still a `fail!()` stub, since libuv is
+    // callback-driven and has no poll-without-blocking primitive; see
+    // `TcpStream::readable`), so `Selector::wait` can't actually run here.
+    // This documents the intended API against a real pair of connections.
+    #[test] #[ignore]
+    fn wait_reports_only_the_stream_written_to() {
+        do run_in_newsched_task {
+            let addr1 = next_test_ip4();
+            let addr2 = next_test_ip4();
+
+            do spawntask_immediately {
+                let mut listener1 = TcpListener::bind(addr1).expect("bind 1 failed");
+                let mut listener2 = TcpListener::bind(addr2).expect("bind 2 failed");
+                let mut stream1 = listener1.accept().expect("accept 1 failed");
+                let mut stream2 = listener2.accept().expect("accept 2 failed");
+
+                let mut streams = [stream1, stream2];
+                let ready = {
+                    let mut selector = Selector::new(streams);
+                    selector.wait()
+                };
+                assert_eq!(ready, ~[1u]);
+
+                let mut buf = [0];
+                streams[1].read(buf);
+                assert_eq!(buf[0], 42);
+            }
+
+            do spawntask_immediately {
+                let _peer1 = TcpStream::connect(addr1).expect("connect 1 failed");
+                let mut peer2 = TcpStream::connect(addr2).expect("connect 2 failed");
+                peer2.write([42]);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Adapters for duplicating bytes as they flow through a `Reader` or
+//! `Writer`, so protocol traffic can be logged (or otherwise observed)
+//! without disturbing delivery to the real destination.
+
+use option::{Option, Some, None};
+use rt::io::{Reader, Writer, io_error};
+
+/// Wraps a `Reader`, forwarding every byte successfully read from it into
+/// a side `Writer` (a log file, say) before handing it back to the
+/// caller. The side writer sees exactly the bytes the wrapped reader
+/// yields, in the same order, and nothing more.
+pub struct TeeReader<R, W> {
+    priv reader: R,
+    priv writer: W,
+    /// Whether an error from the side writer should be raised as
+    /// `io_error` or silently ignored. Either way the read that triggered
+    /// it still returns whatever it read from the primary reader.
+    priv raise_on_error: bool,
+}
+
+impl<R: Reader, W: Writer> TeeReader<R, W> {
+    /// Creates a new `TeeReader`. Side-writer errors are ignored by
+    /// default; use `set_raise_on_error` to have them raised instead.
+    pub fn new(reader: R, writer: W) -> TeeReader<R, W> {
+        TeeReader { reader: reader, writer: writer, raise_on_error: false }
+    }
+
+    /// Controls whether an error writing to the side writer is raised as
+    /// `io_error` (the read that surfaced it still returns whatever it
+    /// read) or silently ignored.
+    pub fn set_raise_on_error(&mut self, raise: bool) {
+        self.raise_on_error = raise;
+    }
+}
+
+impl<R: Reader, W: Writer> Reader for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> Option<uint> {
+        match self.reader.read(buf) {
+            Some(n) => {
+                if self.raise_on_error {
+                    self.writer.write(buf.slice(0, n));
+                } else {
+                    do io_error::cond.trap(|_| {}).in {
+                        self.writer.write(buf.slice(0, n));
+                    }
+                }
+                Some(n)
+            }
+            None => None,
+        }
+    }
+
+    fn eof(&mut self) -> bool {
+        self.reader.eof()
+    }
+}
+
+/// Wraps a list of `Writer`s, forwarding every `write` to each of them in
+/// turn. Useful for sending the same bytes to, say, a socket and a log
+/// file at once.
+pub struct BroadcastWriter<W> {
+    priv writers: ~[W],
+    /// Whether a write error from any but the first writer should be
+    /// raised as `io_error`, or silently ignored so one dead sink (e.g.
+    /// a closed log file) can't take the others down with it.
+    priv raise_on_error: bool,
+}
+
+impl<W: Writer> BroadcastWriter<W> {
+    /// Creates a new `BroadcastWriter` over `writers`. Errors from any
+    /// writer but the first are ignored by default; use
+    /// `set_raise_on_error` to have them raised instead.
+    pub fn new(writers: ~[W]) -> BroadcastWriter<W> {
+        BroadcastWriter { writers: writers, raise_on_error: false }
+    }
+
+    /// Controls whether a write error from any but the first writer is
+    /// raised as `io_error` or silently ignored.
+    pub fn set_raise_on_error(&mut self, raise: bool) {
+        self.raise_on_error = raise;
+    }
+}
+
+impl<W: Writer> Writer for BroadcastWriter<W> {
+    fn write(&mut self, buf: &[u8]) {
+        let raise_on_error = self.raise_on_error;
+        for (i, writer) in self.writers.mut_iter().enumerate() {
+            if i == 0 || raise_on_error {
+                writer.write(buf);
+            } else {
+                do io_error::cond.trap(|_| {}).in {
+                    writer.write(buf);
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        for writer in self.writers.mut_iter() {
+            writer.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rt::test::*;
+    use rt::io::mem::MemWriter;
+    use rt::io::net::tcp::{TcpListener, TcpStream};
+    use rt::io::extensions::ReaderUtil;
+    use rt::io::{Listener, Reader, Writer, Decorator};
+
+    #[test]
+    fn tee_reader_copies_tcp_bytes_into_memory() {
+        do run_in_newsched_task {
+            let addr = next_test_ip4();
+
+            do spawntask_immediately {
+                let listener = TcpListener::bind(addr).expect("bind failed");
+                let mut acceptor = listener.listen();
+                let mut stream = acceptor.accept();
+                stream.write([1, 2, 3, 4, 5]);
+            }
+
+            do spawntask_immediately {
+                let stream = TcpStream::connect(addr).expect("connect failed");
+                let mut tee = TeeReader::new(stream, MemWriter::new());
+                let received = tee.read_to_end();
+                assert_eq!(received, ~[1, 2, 3, 4, 5]);
+
+                let TeeReader { writer: log, _ } = tee;
+                assert_eq!(log.inner(), ~[1, 2, 3, 4, 5]);
+            }
+        }
+    }
+
+    #[test]
+    fn broadcast_writer_sends_to_every_sink() {
+        let mut broadcast = BroadcastWriter::new(~[MemWriter::new(), MemWriter::new()]);
+        broadcast.write([1, 2, 3]);
+
+        let BroadcastWriter { writers: mut sinks, _ } = broadcast;
+        assert_eq!(sinks.shift().inner(), ~[1, 2, 3]);
+        assert_eq!(sinks.shift().inner(), ~[1, 2, 3]);
+    }
+}
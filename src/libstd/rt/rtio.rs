@@ -8,11 +8,12 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use libc;
 use option::*;
 use result::*;
 
 use rt::io::IoError;
-use super::io::net::ip::IpAddr;
+use super::io::net::ip::{IpAddr, SocketAddr};
 use rt::uv::uvio;
 
 // XXX: ~object doesn't work currently so these are some placeholder
@@ -44,9 +45,29 @@ pub trait RemoteCallback {
 }
 
 pub trait IoFactory {
-    fn tcp_connect(&mut self, addr: IpAddr) -> Result<~RtioTcpStreamObject, IoError>;
-    fn tcp_bind(&mut self, addr: IpAddr) -> Result<~RtioTcpListenerObject, IoError>;
-    fn udp_bind(&mut self, addr: IpAddr) -> Result<~RtioUdpSocketObject, IoError>;
+    fn tcp_connect(&mut self, addr: SocketAddr) -> Result<~RtioTcpStreamObject, IoError>;
+    /// Like `tcp_connect`, but binds the socket to `local` before connecting
+    /// to `remote`, letting the caller pick the source address/interface an
+    /// outbound connection originates from.
+    fn tcp_connect_from(&mut self, local: SocketAddr, remote: SocketAddr)
+        -> Result<~RtioTcpStreamObject, IoError>;
+    fn tcp_bind(&mut self, addr: SocketAddr) -> Result<~RtioTcpListenerObject, IoError>;
+    /// Like `tcp_bind`, but sets `SO_REUSEPORT` before binding (Linux
+    /// only), so several independent listeners can share the same port
+    /// with the kernel load-balancing incoming connections across them --
+    /// one accept loop per scheduler thread instead of funneling
+    /// everything through a single listener and its own dispatch.
+    fn tcp_bind_reuseport(&mut self, addr: SocketAddr) -> Result<~RtioTcpListenerObject, IoError>;
+    /// Adopt an already-open socket file descriptor (e.g. one inherited
+    /// via systemd socket activation, or from a caller that did its own
+    /// `socket`/`accept`) as a `TcpStream`. Takes ownership: the returned
+    /// stream closes the fd on drop like any other.
+    fn tcp_open(&mut self, fd: libc::c_int) -> Result<~RtioTcpStreamObject, IoError>;
+    /// As `tcp_open`, but for a socket that's already listening rather
+    /// than connected, e.g. one handed over by socket activation or
+    /// inherited across an `exec` for a zero-downtime restart.
+    fn tcp_listen_open(&mut self, fd: libc::c_int) -> Result<~RtioTcpListenerObject, IoError>;
+    fn udp_bind(&mut self, addr: SocketAddr) -> Result<~RtioUdpSocketObject, IoError>;
     fn timer_init(&mut self) -> Result<~RtioTimerObject, IoError>;
 }
 
@@ -54,25 +75,169 @@ pub trait RtioTcpListener : RtioSocket {
     fn accept(&mut self) -> Result<~RtioTcpStreamObject, IoError>;
     fn accept_simultaneously(&mut self);
     fn dont_accept_simultaneously(&mut self);
+    /// Bound how long `accept` may block waiting for a connection. `None`
+    /// restores blocking indefinitely.
+    fn set_timeout(&mut self, ms: Option<u64>);
+    /// The underlying OS listening socket file descriptor, for handing off
+    /// to another process. Still owned by this listener: closing it
+    /// directly out from under the listener, or racing that close against
+    /// a drop, is the caller's problem.
+    fn as_raw_fd(&self) -> libc::c_int;
+    /// `setsockopt(IPPROTO_IPV6, IPV6_V6ONLY)`, so a listener bound to
+    /// `::` can be switched between dual-stack (also accepting IPv4
+    /// clients as v4-mapped addresses) and IPv6-only. Only meaningful for
+    /// an IPv6 listener; the platform's own default applies until this is
+    /// called.
+    fn set_only_v6(&mut self, only: bool) -> Result<(), IoError>;
+    /// `fcntl(F_SETFD, FD_CLOEXEC)`, so an `exec`'d child doesn't inherit
+    /// this listening socket. Sockets are already close-on-exec by default
+    /// where the platform allows atomic creation (`SOCK_CLOEXEC`); this is
+    /// for turning it off, or setting it explicitly where that atomic path
+    /// isn't available.
+    fn set_cloexec(&mut self, on: bool) -> Result<(), IoError>;
 }
 
 pub trait RtioTcpStream : RtioSocket {
     fn read(&mut self, buf: &mut [u8]) -> Result<uint, IoError>;
     fn write(&mut self, buf: &[u8]) -> Result<(), IoError>;
-    fn peer_name(&mut self) -> IpAddr;
+    /// As `write`, but issues every buffer in `bufs` as a single gathered
+    /// write instead of one call per buffer, so a header and body (say)
+    /// reach the wire in one syscall without first being copied together.
+    fn write_vectored(&mut self, bufs: &[&[u8]]) -> Result<(), IoError>;
+    fn peer_name(&mut self) -> SocketAddr;
     fn control_congestion(&mut self);
     fn nodelay(&mut self);
     fn keepalive(&mut self, delay_in_seconds: uint);
     fn letdie(&mut self);
+    /// Query the kernel's TCP_INFO socket statistics for this connection.
+    fn tcp_info(&mut self) -> TcpInfo;
+    /// `getsockopt(TCP_MAXSEG)`: the current maximum segment size
+    /// negotiated for this connection, read-only diagnostic information
+    /// for performance tuning.
+    fn mss(&mut self) -> Result<uint, IoError>;
+    /// Set `SO_LINGER`. `None` restores the default graceful close;
+    /// `Some(0)` causes close to send an RST, discarding unsent data;
+    /// `Some(n)` causes close to block for up to `n` seconds flushing data.
+    fn set_linger(&mut self, duration_in_seconds: Option<uint>);
+    /// `setsockopt(SO_SNDBUF)`
+    fn set_send_buffer_size(&mut self, bytes: uint) -> Result<(), IoError>;
+    /// `setsockopt(SO_RCVBUF)`
+    fn set_recv_buffer_size(&mut self, bytes: uint) -> Result<(), IoError>;
+    /// `getsockopt(SO_SNDBUF)`
+    fn send_buffer_size(&mut self) -> Result<uint, IoError>;
+    /// `getsockopt(SO_RCVBUF)`
+    fn recv_buffer_size(&mut self) -> Result<uint, IoError>;
+    /// Shut down one or both halves of the connection, without closing
+    /// the underlying socket.
+    fn shutdown(&mut self, how: Shutdown) -> Result<(), IoError>;
+    /// `setsockopt(IPPROTO_TCP, TCP_QUICKACK)`, where the platform has it
+    /// (Linux only); disables the brief delay the kernel otherwise adds
+    /// before ACKing, which request/response workloads pay for on every
+    /// round trip. Elsewhere this is a documented no-op that still
+    /// returns `Ok`, since there's nothing to fail.
+    fn set_quickack(&mut self, on: bool) -> Result<(), IoError>;
+    /// Cheaply checks whether the peer has closed the connection, via a
+    /// zero-length `MSG_PEEK` read: no data and no error means the peer
+    /// is gone, `WouldBlock` means the connection is still alive and
+    /// just idle, and any other error also counts as disconnected.
+    fn is_connected(&mut self) -> bool;
+    /// The underlying OS file descriptor, for interop with code that
+    /// wants to call syscalls on it directly. Still owned by this stream;
+    /// closing it out from under the stream is the caller's problem.
+    fn as_raw_fd(&self) -> libc::c_int;
+    /// `dup`s the underlying file descriptor into a second, independent
+    /// stream object, so each handle can have its own socket options
+    /// (e.g. one buffered, one with `TCP_NODELAY`) while sharing the same
+    /// underlying connection.
+    fn try_clone(&mut self) -> Result<~RtioTcpStreamObject, IoError>;
+    /// `setsockopt(SO_OOBINLINE)`. When on, urgent data sent with
+    /// `send_oob` is delivered inline in the normal read stream instead
+    /// of needing a separate out-of-band read.
+    fn set_oob_inline(&mut self, on: bool) -> Result<(), IoError>;
+    /// `send(..., MSG_OOB)` of a single byte of TCP urgent data, for
+    /// interop with legacy protocols (telnet, rlogin) that use it as an
+    /// out-of-band signal.
+    fn send_oob(&mut self, byte: u8) -> Result<(), IoError>;
+    /// Zero-timeout readiness check: would a write submitted right now
+    /// complete without blocking?
+    fn writable(&mut self) -> Result<bool, IoError>;
+    /// Zero-timeout readiness check: would a read submitted right now
+    /// complete without blocking?
+    fn readable(&mut self) -> Result<bool, IoError>;
+    /// `setsockopt(TCP_KEEPIDLE, TCP_KEEPINTVL, TCP_KEEPCNT)` (or the
+    /// platform's nearest equivalent), applied together so a caller tuning
+    /// one doesn't have to reason about the others' defaults.
+    fn set_keepalive_config(&mut self, cfg: KeepaliveConfig) -> Result<(), IoError>;
+    /// `setsockopt(IP_TOS)` on an IPv4 socket, or `setsockopt(IPV6_TCLASS)`
+    /// on IPv6, marking the TOS/DSCP byte on outgoing packets for
+    /// router-level traffic prioritization (e.g. VoIP, video). Which
+    /// option this uses depends on the connected socket's address family;
+    /// the caller doesn't need to know or care.
+    fn set_tos(&mut self, tos: u8) -> Result<(), IoError>;
+    /// Reads back the TOS/DSCP byte last set via `set_tos`, or the
+    /// platform's default if it was never called.
+    fn tos(&mut self) -> Result<u8, IoError>;
+    /// `getsockopt(SO_ERROR)`, clearing it as a side effect the way the
+    /// underlying syscall does. Surfaces an error a prior operation left
+    /// pending on the socket without one having been reported yet, most
+    /// importantly a failed non-blocking connect: the connect call itself
+    /// returns immediately with the attempt merely underway, and this is
+    /// how a caller later finds out whether it actually succeeded.
+    /// `None` means the socket has nothing pending.
+    fn take_socket_error(&mut self) -> Option<IoError>;
+    /// `fcntl(F_SETFD, FD_CLOEXEC)`, so an `exec`'d child doesn't inherit
+    /// this connected socket -- otherwise a leaked descriptor can hold a
+    /// port "in use" or leave a privileged connection reachable from code
+    /// that never asked for it. Sockets are already close-on-exec by
+    /// default where the platform allows atomic creation (`SOCK_CLOEXEC`);
+    /// this is for turning it off, or setting it explicitly elsewhere.
+    fn set_cloexec(&mut self, on: bool) -> Result<(), IoError>;
+}
+
+/// Which half (or both) of a duplex connection to shut down.
+pub enum Shutdown {
+    /// No further reads will succeed; the peer sees this as EOF.
+    ShutdownRead,
+    /// No further writes are possible; the peer's reads see EOF once it
+    /// has drained whatever was already in flight.
+    ShutdownWrite,
+    /// Both directions.
+    ShutdownBoth,
+}
+
+/// A snapshot of the OS's `TCP_INFO` socket statistics for a `TcpStream`.
+/// Mirrors the handful of fields most host kernels agree on; not every
+/// backend or platform can populate all of them.
+pub struct TcpInfo {
+    /// Smoothed round-trip time estimate, in microseconds.
+    rtt: u32,
+    /// Round-trip time variance, in microseconds.
+    rttvar: u32,
+    /// Current congestion window, in segments.
+    snd_cwnd: u32,
+    /// Number of retransmitted segments.
+    retransmits: u32,
+}
+
+/// Tuning for the kernel's TCP keepalive probes, beyond the single idle
+/// interval `RtioTcpStream::keepalive` already covers.
+pub struct KeepaliveConfig {
+    /// Seconds of idleness before the first probe is sent.
+    idle: u64,
+    /// Seconds between probes once they've started.
+    interval: u64,
+    /// Number of unanswered probes before the connection is considered
+    /// dead.
+    count: u32,
 }
 
 pub trait RtioSocket {
-    fn socket_name(&mut self) -> IpAddr;
+    fn socket_name(&mut self) -> SocketAddr;
 }
 
 pub trait RtioUdpSocket : RtioSocket {
-    fn recvfrom(&mut self, buf: &mut [u8]) -> Result<(uint, IpAddr), IoError>;
-    fn sendto(&mut self, buf: &[u8], dst: IpAddr) -> Result<(), IoError>;
+    fn recvfrom(&mut self, buf: &mut [u8]) -> Result<(uint, SocketAddr), IoError>;
+    fn sendto(&mut self, buf: &[u8], dst: SocketAddr) -> Result<(), IoError>;
 
     fn join_multicast(&mut self, multi: IpAddr);
     fn leave_multicast(&mut self, multi: IpAddr);
@@ -62,6 +62,11 @@ pub struct LocalStorage(*c_void, Option<extern "Rust" fn(*c_void)>);
 
 pub struct Unwinder {
     unwinding: bool,
+    /// The description passed to the `fail!` that's currently unwinding
+    /// this task, if any. Set by `sys::begin_unwind_` just before the
+    /// unwind starts, so anything running as part of it (destructors,
+    /// `Finally` blocks) can still read what went wrong.
+    fail_message: Option<~str>,
 }
 
 impl Task {
@@ -85,7 +90,7 @@ impl Task {
             gc: GarbageCollector,
             storage: LocalStorage(ptr::null(), None),
             logger: StdErrLogger,
-            unwinder: Unwinder { unwinding: false },
+            unwinder: Unwinder { unwinding: false, fail_message: None },
             home: Some(home),
             taskgroup: None,
             death: Death::new(),
@@ -104,7 +109,7 @@ impl Task {
             storage: LocalStorage(ptr::null(), None),
             logger: StdErrLogger,
             home: Some(home),
-            unwinder: Unwinder { unwinding: false },
+            unwinder: Unwinder { unwinding: false, fail_message: None },
             taskgroup: None,
             // FIXME(#7544) make watching optional
             death: self.death.new_child(),
@@ -16,7 +16,7 @@ use clone::Clone;
 use container::Container;
 use iterator::IteratorUtil;
 use vec::{OwnedVector, MutableVector};
-use super::io::net::ip::{IpAddr, Ipv4, Ipv6};
+use super::io::net::ip::{Ipv4, Ipv6, SocketAddr};
 use rt::sched::Scheduler;
 use rt::local::Local;
 use unstable::run_in_bare_thread;
@@ -338,13 +338,13 @@ pub fn next_test_port() -> u16 {
 }
 
 /// Get a unique IPv4 localhost:port pair starting at 9600
-pub fn next_test_ip4() -> IpAddr {
-    Ipv4(127, 0, 0, 1, next_test_port())
+pub fn next_test_ip4() -> SocketAddr {
+    SocketAddr { ip: Ipv4(127, 0, 0, 1), port: next_test_port() }
 }
 
 /// Get a unique IPv6 localhost:port pair starting at 9600
-pub fn next_test_ip6() -> IpAddr {
-    Ipv6(0, 0, 0, 0, 0, 0, 0, 1, next_test_port())
+pub fn next_test_ip6() -> SocketAddr {
+    SocketAddr { ip: Ipv6(0, 0, 0, 0, 0, 0, 0, 1, 0), port: next_test_port() }
 }
 
 /*
@@ -47,7 +47,7 @@ use libc::{c_void, c_int, size_t, malloc, free};
 use cast::transmute;
 use ptr::null;
 use unstable::finally::Finally;
-use rt::io::net::ip::IpAddr;
+use rt::io::net::ip::SocketAddr;
 
 use rt::io::IoError;
 
@@ -128,7 +128,7 @@ pub type ConnectionCallback = ~fn(StreamWatcher, Option<UvError>);
 pub type FsCallback = ~fn(FsRequest, Option<UvError>);
 pub type TimerCallback = ~fn(TimerWatcher, Option<UvError>);
 pub type AsyncCallback = ~fn(AsyncWatcher, Option<UvError>);
-pub type UdpReceiveCallback = ~fn(UdpWatcher, int, Buf, IpAddr, uint, Option<UvError>);
+pub type UdpReceiveCallback = ~fn(UdpWatcher, int, Buf, SocketAddr, uint, Option<UvError>);
 pub type UdpSendCallback = ~fn(UdpWatcher, Option<UvError>);
 
 
@@ -268,7 +268,12 @@ pub fn uv_error_to_io_error(uverr: UvError) -> IoError {
             EPIPE => BrokenPipe,
             _ => {
                 rtdebug!("uverr.code %u", uverr.code as uint);
-                // XXX: Need to map remaining uv error types
+                // XXX: Need to map remaining uv error types, notably
+                // EINTR -> Interrupted and EMFILE/ENFILE -> ResourceExhausted
+                // (see IoErrorKind), so that callers like TcpListener::accept
+                // can retry the former and back off on the latter instead of
+                // treating both as an opaque OtherIoError. Left unmapped for
+                // now because `uvll` doesn't bind those codes yet.
                 OtherIoError
             }
         };
@@ -276,7 +281,8 @@ pub fn uv_error_to_io_error(uverr: UvError) -> IoError {
         IoError {
             kind: kind,
             desc: desc,
-            detail: None
+            detail: None,
+            errno: Some(uverr.sys_errno_ as int)
         }
     }
 }
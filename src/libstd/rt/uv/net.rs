@@ -15,7 +15,7 @@ use rt::uv::uvll::*;
 use rt::uv::{AllocCallback, ConnectionCallback, ReadCallback, UdpReceiveCallback, UdpSendCallback};
 use rt::uv::{Loop, Watcher, Request, UvError, Buf, NativeHandle, NullCallback,
              status_to_maybe_uv_error};
-use rt::io::net::ip::{IpAddr, Ipv4, Ipv6};
+use rt::io::net::ip::{Ipv4, Ipv6, SocketAddr};
 use rt::uv::last_uv_error;
 use vec;
 use str;
@@ -39,27 +39,28 @@ fn sockaddr_to_UvIpAddr(addr: *uvll::sockaddr) -> UvIpAddr {
     }
 }
 
-fn ip_as_uv_ip<T>(addr: IpAddr, f: &fn(UvIpAddr) -> T) -> T {
-    let malloc = match addr {
+fn ip_as_uv_ip<T>(addr: SocketAddr, f: &fn(UvIpAddr) -> T) -> T {
+    let malloc = match addr.ip {
         Ipv4(*) => malloc_ip4_addr,
         Ipv6(*) => malloc_ip6_addr,
     };
-    let wrap = match addr {
+    let wrap = match addr.ip {
         Ipv4(*) => UvIpv4,
         Ipv6(*) => UvIpv6,
     };
-    let ip_str = match addr {
-        Ipv4(x1, x2, x3, x4, _) =>
+    let ip_str = match addr.ip {
+        Ipv4(x1, x2, x3, x4) =>
             fmt!("%u.%u.%u.%u", x1 as uint, x2 as uint, x3 as uint, x4 as uint),
-        Ipv6(x1, x2, x3, x4, x5, x6, x7, x8, _) =>
+        // `malloc_ip6_addr` has no scope-id parameter, so the scope id (if
+        // any) is dropped here; it can't be threaded through the uv/libuv
+        // sockaddr construction with the FFI surface this tree wraps.
+        Ipv6(x1, x2, x3, x4, x5, x6, x7, x8, _scope_id) =>
             fmt!("%x:%x:%x:%x:%x:%x:%x:%x",
                   x1 as uint, x2 as uint, x3 as uint, x4 as uint,
                   x5 as uint, x6 as uint, x7 as uint, x8 as uint),
     };
-    let port = match addr {
-        Ipv4(_, _, _, _, p) | Ipv6(_, _, _, _, _, _, _, _, p) => p as int
-    };
-    let free = match addr {
+    let port = addr.port as int;
+    let free = match addr.ip {
         Ipv4(*) => free_ip4_addr,
         Ipv6(*) => free_ip6_addr,
     };
@@ -72,7 +73,7 @@ fn ip_as_uv_ip<T>(addr: IpAddr, f: &fn(UvIpAddr) -> T) -> T {
     }
 }
 
-fn uv_ip_as_ip<T>(addr: UvIpAddr, f: &fn(IpAddr) -> T) -> T {
+fn uv_ip_as_ip<T>(addr: UvIpAddr, f: &fn(SocketAddr) -> T) -> T {
     let ip_size = match addr {
         UvIpv4(*) => 4/*groups of*/ * 3/*digits separated by*/ + 3/*periods*/,
         UvIpv6(*) => 8/*groups of*/ * 4/*hex digits separated by*/ + 7 /*colons*/,
@@ -102,7 +103,7 @@ fn uv_ip_as_ip<T>(addr: UvIpAddr, f: &fn(IpAddr) -> T) -> T {
                       .transform(|s: &str| -> u8 { FromStr::from_str(s).unwrap() })
                       .collect();
             assert_eq!(ip.len(), 4);
-            Ipv4(ip[0], ip[1], ip[2], ip[3], ip_port)
+            Ipv4(ip[0], ip[1], ip[2], ip[3])
         },
         UvIpv6(*) => {
             let ip: ~[u16] = {
@@ -125,15 +126,17 @@ fn uv_ip_as_ip<T>(addr: UvIpAddr, f: &fn(IpAddr) -> T) -> T {
                 }
             };
             assert_eq!(ip.len(), 8);
-            Ipv6(ip[0], ip[1], ip[2], ip[3], ip[4], ip[5], ip[6], ip[7], ip_port)
+            // `ip6_name` doesn't report a scope id either, so round-tripped
+            // addresses always come back with scope id 0.
+            Ipv6(ip[0], ip[1], ip[2], ip[3], ip[4], ip[5], ip[6], ip[7], 0)
         },
     };
 
     // finally run the closure
-    f(ip)
+    f(SocketAddr { ip: ip, port: ip_port })
 }
 
-fn uv_ip_to_ip(addr: UvIpAddr) -> IpAddr {
+fn uv_ip_to_ip(addr: UvIpAddr) -> SocketAddr {
     use util;
     uv_ip_as_ip(addr, util::id)
 }
@@ -194,6 +197,14 @@ impl StreamWatcher {
     }
 
     pub fn write(&mut self, buf: Buf, cb: ConnectionCallback) {
+        self.write_vectored([buf], cb)
+    }
+
+    /// As `write`, but hands every buffer in `bufs` to a single underlying
+    /// `uv_write`, which libuv gathers into one `writev` syscall rather than
+    /// issuing (or requiring the caller to first copy into) one contiguous
+    /// buffer.
+    pub fn write_vectored(&mut self, bufs: &[Buf], cb: ConnectionCallback) {
         {
             let data = self.get_watcher_data();
             assert!(data.write_cb.is_none());
@@ -202,7 +213,7 @@ impl StreamWatcher {
 
         let req = WriteRequest::new();
         unsafe {
-        assert_eq!(0, uvll::write(req.native_handle(), self.native_handle(), [buf], write_cb));
+        assert_eq!(0, uvll::write(req.native_handle(), self.native_handle(), bufs, write_cb));
         }
 
         extern fn write_cb(req: *uvll::uv_write_t, status: c_int) {
@@ -264,7 +275,7 @@ impl TcpWatcher {
         }
     }
 
-    pub fn bind(&mut self, address: IpAddr) -> Result<(), UvError> {
+    pub fn bind(&mut self, address: SocketAddr) -> Result<(), UvError> {
         do ip_as_uv_ip(address) |addr| {
             let result = unsafe {
                 match addr {
@@ -279,7 +290,7 @@ impl TcpWatcher {
         }
     }
 
-    pub fn connect(&mut self, address: IpAddr, cb: ConnectionCallback) {
+    pub fn connect(&mut self, address: SocketAddr, cb: ConnectionCallback) {
         unsafe {
             assert!(self.get_watcher_data().connect_cb.is_none());
             self.get_watcher_data().connect_cb = Some(cb);
@@ -359,7 +370,7 @@ impl UdpWatcher {
         }
     }
 
-    pub fn bind(&mut self, address: IpAddr) -> Result<(), UvError> {
+    pub fn bind(&mut self, address: SocketAddr) -> Result<(), UvError> {
         do ip_as_uv_ip(address) |addr| {
             let result = unsafe {
                 match addr {
@@ -412,7 +423,7 @@ impl UdpWatcher {
         unsafe { uvll::udp_recv_stop(self.native_handle()); }
     }
 
-    pub fn send(&mut self, buf: Buf, address: IpAddr, cb: UdpSendCallback) {
+    pub fn send(&mut self, buf: Buf, address: SocketAddr, cb: UdpSendCallback) {
         {
             let data = self.get_watcher_data();
             assert!(data.udp_send_cb.is_none());
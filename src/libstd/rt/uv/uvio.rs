@@ -15,13 +15,14 @@ use cell::Cell;
 use cast;
 use cast::transmute;
 use clone::Clone;
+use libc;
 use rt::io::IoError;
-use rt::io::net::ip::IpAddr;
+use rt::io::net::ip::{IpAddr, SocketAddr};
 use rt::uv::*;
 use rt::uv::idle::IdleWatcher;
 use rt::rtio::*;
 use rt::sched::Scheduler;
-use rt::io::{standard_error, OtherIoError};
+use rt::io::{standard_error, OtherIoError, TimedOut};
 use rt::tube::Tube;
 use rt::local::Local;
 use unstable::sync::{Exclusive, exclusive};
@@ -34,6 +35,34 @@ use unstable::sync::{Exclusive, exclusive};
                             run_in_newsched_task};
 
 
+/// A count of live TCP socket fds, maintained only under `#[cfg(test)]` so
+/// tests can assert that a scenario doesn't leak fds past its own scope.
+#[cfg(test)]
+static mut OPEN_SOCKET_COUNT: uint = 0;
+
+#[cfg(test)]
+fn socket_opened() {
+    unsafe { OPEN_SOCKET_COUNT += 1; }
+}
+
+#[cfg(test)]
+fn socket_closed() {
+    unsafe { OPEN_SOCKET_COUNT -= 1; }
+}
+
+#[cfg(not(test))]
+fn socket_opened() { }
+
+#[cfg(not(test))]
+fn socket_closed() { }
+
+/// The number of `UvTcpStream`/`UvTcpListener` fds currently open. Only
+/// meaningful in test builds; see `OPEN_SOCKET_COUNT` above.
+#[cfg(test)]
+pub fn open_socket_count() -> uint {
+    unsafe { OPEN_SOCKET_COUNT }
+}
+
 pub struct UvEventLoop {
     uvio: UvIoFactory
 }
@@ -198,7 +227,7 @@ impl IoFactory for UvIoFactory {
     // Connect to an address and return a new stream
     // NB: This blocks the task waiting on the connection.
     // It would probably be better to return a future
-    fn tcp_connect(&mut self, addr: IpAddr) -> Result<~RtioTcpStreamObject, IoError> {
+    fn tcp_connect(&mut self, addr: SocketAddr) -> Result<~RtioTcpStreamObject, IoError> {
         // Create a cell in the task to hold the result. We will fill
         // the cell before resuming the task.
         let result_cell = Cell::new_empty();
@@ -220,6 +249,7 @@ impl IoFactory for UvIoFactory {
                 rtdebug!("connect: in connect callback");
                 if status.is_none() {
                     rtdebug!("status is none");
+                    socket_opened();
                     let res = Ok(~UvTcpStream(stream_watcher));
 
                     // Store the stream in the task's stack
@@ -245,7 +275,55 @@ impl IoFactory for UvIoFactory {
         return result_cell.take();
     }
 
-    fn tcp_bind(&mut self, addr: IpAddr) -> Result<~RtioTcpListenerObject, IoError> {
+    fn tcp_connect_from(&mut self, local: SocketAddr, remote: SocketAddr)
+        -> Result<~RtioTcpStreamObject, IoError> {
+        let result_cell = Cell::new_empty();
+        let result_cell_ptr: *Cell<Result<~RtioTcpStreamObject, IoError>> = &result_cell;
+
+        let scheduler = Local::take::<Scheduler>();
+        assert!(scheduler.in_task_context());
+
+        do scheduler.deschedule_running_task_and_then |sched, task| {
+            assert!(!sched.in_task_context());
+            let mut tcp_watcher = TcpWatcher::new(self.uv_loop());
+            let task_cell = Cell::new(task);
+
+            match tcp_watcher.bind(local) {
+                Ok(()) => {
+                    do tcp_watcher.connect(remote) |stream_watcher, status| {
+                        if status.is_none() {
+                            socket_opened();
+                            let res = Ok(~UvTcpStream(stream_watcher));
+                            unsafe { (*result_cell_ptr).put_back(res); }
+                            let scheduler = Local::take::<Scheduler>();
+                            scheduler.resume_blocked_task_immediately(task_cell.take());
+                        } else {
+                            let task_cell = Cell::new(task_cell.take());
+                            do stream_watcher.close {
+                                let res = Err(uv_error_to_io_error(status.get()));
+                                unsafe { (*result_cell_ptr).put_back(res); }
+                                let scheduler = Local::take::<Scheduler>();
+                                scheduler.resume_blocked_task_immediately(task_cell.take());
+                            }
+                        }
+                    }
+                }
+                Err(uverr) => {
+                    let res = Err(uv_error_to_io_error(uverr));
+                    unsafe { (*result_cell_ptr).put_back(res); }
+                    do tcp_watcher.as_stream().close {
+                        let scheduler = Local::take::<Scheduler>();
+                        scheduler.resume_blocked_task_immediately(task_cell.take());
+                    }
+                }
+            }
+        }
+
+        assert!(!result_cell.is_empty());
+        return result_cell.take();
+    }
+
+    fn tcp_bind(&mut self, addr: SocketAddr) -> Result<~RtioTcpListenerObject, IoError> {
         let mut watcher = TcpWatcher::new(self.uv_loop());
         match watcher.bind(addr) {
             Ok(_) => Ok(~UvTcpListener::new(watcher)),
@@ -263,7 +341,27 @@ impl IoFactory for UvIoFactory {
         }
     }
 
-    fn udp_bind(&mut self, addr: IpAddr) -> Result<~RtioUdpSocketObject, IoError> {
+    // XXX implement: SO_REUSEPORT has to be set on the raw socket between
+    // `socket()` and `bind()`, but libuv only exposes `uv_tcp_bind` as a
+    // single opaque step, with no hook to touch the fd in between and no
+    // `uv_tcp_open` in this tree's bindings to swap in a pre-configured
+    // one afterwards either.
+    fn tcp_bind_reuseport(&mut self, _addr: SocketAddr) -> Result<~RtioTcpListenerObject, IoError> {
+        fail!();
+    }
+
+    // XXX implement: adopting an existing fd needs `uv_tcp_open`, which
+    // this tree's uvll bindings don't wrap.
+    fn tcp_open(&mut self, _fd: libc::c_int) -> Result<~RtioTcpStreamObject, IoError> {
+        fail!();
+    }
+
+    // XXX implement: same `uv_tcp_open` gap as `tcp_open` above.
+    fn tcp_listen_open(&mut self, _fd: libc::c_int) -> Result<~RtioTcpListenerObject, IoError> {
+        fail!();
+    }
+
+    fn udp_bind(&mut self, addr: SocketAddr) -> Result<~RtioUdpSocketObject, IoError> {
         let mut watcher = UdpWatcher::new(self.uv_loop());
         match watcher.bind(addr) {
             Ok(_) => Ok(~UvUdpSocket(watcher)),
@@ -290,15 +388,18 @@ impl IoFactory for UvIoFactory {
 pub struct UvTcpListener {
     watcher: TcpWatcher,
     listening: bool,
-    incoming_streams: Tube<Result<~RtioTcpStreamObject, IoError>>
+    incoming_streams: Tube<Result<~RtioTcpStreamObject, IoError>>,
+    timeout_ms: Option<u64>,
 }
 
 impl UvTcpListener {
     fn new(watcher: TcpWatcher) -> UvTcpListener {
+        socket_opened();
         UvTcpListener {
             watcher: watcher,
             listening: false,
-            incoming_streams: Tube::new()
+            incoming_streams: Tube::new(),
+            timeout_ms: None,
         }
     }
 
@@ -307,6 +408,7 @@ impl UvTcpListener {
 
 impl Drop for UvTcpListener {
     fn drop(&self) {
+        socket_closed();
         let watcher = self.watcher();
         let scheduler = Local::take::<Scheduler>();
         do scheduler.deschedule_running_task_and_then |_, task| {
@@ -321,7 +423,7 @@ impl Drop for UvTcpListener {
 
 impl RtioSocket for UvTcpListener {
     // XXX implement
-    fn socket_name(&mut self) -> IpAddr { fail!(); }
+    fn socket_name(&mut self) -> SocketAddr { fail!(); }
 }
 
 impl RtioTcpListener for UvTcpListener {
@@ -329,40 +431,72 @@ impl RtioTcpListener for UvTcpListener {
     fn accept(&mut self) -> Result<~RtioTcpStreamObject, IoError> {
         rtdebug!("entering listen");
 
-        if self.listening {
-            return self.incoming_streams.recv();
-        }
-
-        self.listening = true;
+        if !self.listening {
+            self.listening = true;
+
+            let server_tcp_watcher = self.watcher();
+            let incoming_streams_cell = Cell::new(self.incoming_streams.clone());
+
+            let incoming_streams_cell = Cell::new(incoming_streams_cell.take());
+            let mut server_tcp_watcher = server_tcp_watcher;
+            do server_tcp_watcher.listen |mut server_stream_watcher, status| {
+                let maybe_stream = if status.is_none() {
+                    let mut loop_ = server_stream_watcher.event_loop();
+                    let client_tcp_watcher = TcpWatcher::new(&mut loop_);
+                    let client_tcp_watcher = client_tcp_watcher.as_stream();
+                    // XXX: Need's to be surfaced in interface
+                    server_stream_watcher.accept(client_tcp_watcher);
+                    socket_opened();
+                    Ok(~UvTcpStream(client_tcp_watcher))
+                } else {
+                    Err(standard_error(OtherIoError))
+                };
 
-        let server_tcp_watcher = self.watcher();
-        let incoming_streams_cell = Cell::new(self.incoming_streams.clone());
+                let mut incoming_streams = incoming_streams_cell.take();
+                incoming_streams.send(maybe_stream);
+                incoming_streams_cell.put_back(incoming_streams);
+            }
+        }
 
-        let incoming_streams_cell = Cell::new(incoming_streams_cell.take());
-        let mut server_tcp_watcher = server_tcp_watcher;
-        do server_tcp_watcher.listen |mut server_stream_watcher, status| {
-            let maybe_stream = if status.is_none() {
-                let mut loop_ = server_stream_watcher.event_loop();
-                let client_tcp_watcher = TcpWatcher::new(&mut loop_);
-                let client_tcp_watcher = client_tcp_watcher.as_stream();
-                // XXX: Need's to be surfaced in interface
-                server_stream_watcher.accept(client_tcp_watcher);
-                Ok(~UvTcpStream(client_tcp_watcher))
-            } else {
-                Err(standard_error(OtherIoError))
-            };
+        match self.timeout_ms {
+            None => self.incoming_streams.recv(),
+            Some(ms) => {
+                // Race the real accept against a one-shot timer: whichever
+                // sends into the tube first wins. The loser's send (if the
+                // timer still fires after a connection already arrived)
+                // just becomes the next `accept` call's result, since we
+                // stop it as soon as we can -- a small, harmless window
+                // given this event loop is single-threaded.
+                let mut loop_ = self.watcher().event_loop();
+                let mut timer = TimerWatcher::new(&mut loop_);
+                let mut timeout_streams = self.incoming_streams.clone();
+                do timer.start(ms, 0) |_, _| {
+                    timeout_streams.send(Err(standard_error(TimedOut)));
+                }
 
-            let mut incoming_streams = incoming_streams_cell.take();
-            incoming_streams.send(maybe_stream);
-            incoming_streams_cell.put_back(incoming_streams);
+                let result = self.incoming_streams.recv();
+                timer.stop();
+                timer.close(||());
+                result
+            }
         }
-
-        return self.incoming_streams.recv();
     }
 
     // XXX implement
     fn accept_simultaneously(&mut self) { fail!(); }
     fn dont_accept_simultaneously(&mut self) { fail!(); }
+    fn set_timeout(&mut self, ms: Option<u64>) { self.timeout_ms = ms; }
+    // XXX implement: needs `uv_fileno`, which this tree's uvll bindings
+    // don't wrap (see `UvTcpStream::as_raw_fd` below).
+    fn as_raw_fd(&self) -> libc::c_int { fail!(); }
+    // XXX implement: IPV6_V6ONLY needs a raw `setsockopt`, which (like
+    // TCP_QUICKACK and SO_OOBINLINE elsewhere in this file) this backend
+    // has no way to reach without a raw fd and a `setsockopt` binding
+    // neither of which libuv exposes here.
+    fn set_only_v6(&mut self, _only: bool) -> Result<(), IoError> { fail!(); }
+    // XXX implement: needs a raw `fcntl`, which (like `as_raw_fd` above)
+    // this backend can't reach without `uv_fileno`.
+    fn set_cloexec(&mut self, _on: bool) -> Result<(), IoError> { fail!(); }
 }
 
 // FIXME #6090: Prefer newtype structs but Drop doesn't work
@@ -370,6 +504,7 @@ pub struct UvTcpStream(StreamWatcher);
 
 impl Drop for UvTcpStream {
     fn drop(&self) {
+        socket_closed();
         rtdebug!("closing tcp stream");
         let scheduler = Local::take::<Scheduler>();
         do scheduler.deschedule_running_task_and_then |_, task| {
@@ -384,7 +519,7 @@ impl Drop for UvTcpStream {
 
 impl RtioSocket for UvTcpStream {
     // XXX implement
-    fn socket_name(&mut self) -> IpAddr { fail!(); }
+    fn socket_name(&mut self) -> SocketAddr { fail!(); }
 }
 
 impl RtioTcpStream for UvTcpStream {
@@ -459,12 +594,106 @@ impl RtioTcpStream for UvTcpStream {
         return result_cell.take();
     }
 
+    fn write_vectored(&mut self, bufs: &[&[u8]]) -> Result<(), IoError> {
+        let result_cell = Cell::new_empty();
+        let result_cell_ptr: *Cell<Result<(), IoError>> = &result_cell;
+        let scheduler = Local::take::<Scheduler>();
+        assert!(scheduler.in_task_context());
+        let bufs_ptr: *&[&[u8]] = &bufs;
+        do scheduler.deschedule_running_task_and_then |_, task| {
+            let task_cell = Cell::new(task);
+            let mut uv_bufs: ~[Buf] = ~[];
+            unsafe {
+                for (*bufs_ptr).iter().advance |buf| {
+                    uv_bufs.push(slice_to_uv_buf(*buf));
+                }
+            }
+            let mut watcher = **self;
+            do watcher.write_vectored(uv_bufs) |_watcher, status| {
+                let result = if status.is_none() {
+                    Ok(())
+                } else {
+                    Err(uv_error_to_io_error(status.unwrap()))
+                };
+
+                unsafe { (*result_cell_ptr).put_back(result); }
+
+                let scheduler = Local::take::<Scheduler>();
+                scheduler.resume_blocked_task_immediately(task_cell.take());
+            }
+        }
+
+        assert!(!result_cell.is_empty());
+        return result_cell.take();
+    }
+
     // XXX implement
-    fn peer_name(&mut self) -> IpAddr { fail!(); }
+    fn peer_name(&mut self) -> SocketAddr { fail!(); }
     fn control_congestion(&mut self) { fail!(); }
     fn nodelay(&mut self) { fail!(); }
     fn keepalive(&mut self, _delay_in_seconds: uint) { fail!(); }
     fn letdie(&mut self) { fail!(); }
+    fn tcp_info(&mut self) -> TcpInfo { fail!() }
+    // XXX implement: TCP_MAXSEG needs a raw `getsockopt`, which (like the
+    // other socket options in this file) this backend has no way to reach
+    // without a raw fd and a `getsockopt` binding neither of which libuv
+    // exposes here.
+    fn mss(&mut self) -> Result<uint, IoError> { fail!(); }
+    fn set_linger(&mut self, _duration_in_seconds: Option<uint>) { fail!(); }
+    fn set_send_buffer_size(&mut self, _bytes: uint) -> Result<(), IoError> { fail!(); }
+    fn set_recv_buffer_size(&mut self, _bytes: uint) -> Result<(), IoError> { fail!(); }
+    fn send_buffer_size(&mut self) -> Result<uint, IoError> { fail!(); }
+    fn recv_buffer_size(&mut self) -> Result<uint, IoError> { fail!(); }
+    fn shutdown(&mut self, _how: Shutdown) -> Result<(), IoError> { fail!(); }
+    // XXX implement: libuv doesn't expose setsockopt (or a raw fd to call
+    // it on ourselves), so there's no way to reach TCP_QUICKACK from here.
+    fn set_quickack(&mut self, _on: bool) -> Result<(), IoError> { fail!(); }
+    // XXX implement: reading with MSG_PEEK needs a raw recv() call this
+    // backend has no way to make; `read_start`/`read_stop` only give us
+    // libuv's own buffered stream reads, which consume what they read.
+    fn is_connected(&mut self) -> bool { fail!(); }
+    // XXX implement: extracting the OS fd from a `uv_tcp_t` needs
+    // `uv_fileno`, which this tree's uvll bindings don't wrap.
+    fn as_raw_fd(&self) -> libc::c_int { fail!(); }
+    // XXX implement: needs both `as_raw_fd` (see above) and a `dup` +
+    // `tcp_open` round trip, neither of which this backend can do yet.
+    fn try_clone(&mut self) -> Result<~RtioTcpStreamObject, IoError> { fail!(); }
+    // XXX implement: SO_OOBINLINE needs `setsockopt`, which (like
+    // TCP_QUICKACK above) this backend has no way to reach without a raw
+    // fd and a `setsockopt` binding neither of which libuv exposes here.
+    fn set_oob_inline(&mut self, _on: bool) -> Result<(), IoError> { fail!(); }
+    // XXX implement: MSG_OOB needs a raw `send()` call this backend has
+    // no way to make; libuv's own write path has no urgent-data flag.
+    fn send_oob(&mut self, _byte: u8) -> Result<(), IoError> { fail!(); }
+    // XXX implement: libuv is callback-driven, not poll-driven -- there's
+    // no `uv_tcp_t` operation that asks "would a write submitted right
+    // now block?" without actually submitting one. Reaching real
+    // readiness would need a raw fd (see `as_raw_fd` above) to `select`/
+    // `poll` on ourselves, which this backend doesn't have either.
+    fn writable(&mut self) -> Result<bool, IoError> { fail!(); }
+    // XXX implement: same gap as `writable` above, mirrored for reads.
+    fn readable(&mut self) -> Result<bool, IoError> { fail!(); }
+    // XXX implement: libuv's own `uv_tcp_keepalive` only takes an
+    // enable flag and the idle delay (see `keepalive` above, itself still
+    // a `fail!()` stub) -- there's no libuv call for TCP_KEEPINTVL or
+    // TCP_KEEPCNT, so applying the full config needs a raw `setsockopt`
+    // this backend has no way to reach, same as `set_quickack`.
+    fn set_keepalive_config(&mut self, _cfg: KeepaliveConfig) -> Result<(), IoError> { fail!(); }
+    // XXX implement: IP_TOS/IPV6_TCLASS need a raw `setsockopt`, which
+    // (like TCP_QUICKACK and SO_OOBINLINE above) this backend has no way
+    // to reach without a raw fd and a `setsockopt` binding neither of
+    // which libuv exposes here.
+    fn set_tos(&mut self, _tos: u8) -> Result<(), IoError> { fail!(); }
+    // XXX implement: same gap as `set_tos` above, mirrored for the getter.
+    fn tos(&mut self) -> Result<u8, IoError> { fail!(); }
+    // XXX implement: SO_ERROR needs a raw `getsockopt`, which (like the
+    // other socket options above) this backend has no way to reach
+    // without a raw fd and a `setsockopt`/`getsockopt` binding neither of
+    // which libuv exposes here.
+    fn take_socket_error(&mut self) -> Option<IoError> { fail!(); }
+    // XXX implement: needs a raw `fcntl`, which (like `as_raw_fd` above)
+    // this backend can't reach without `uv_fileno`.
+    fn set_cloexec(&mut self, _on: bool) -> Result<(), IoError> { fail!(); }
 }
 
 pub struct UvUdpSocket(UdpWatcher);
@@ -485,13 +714,13 @@ impl Drop for UvUdpSocket {
 
 impl RtioSocket for UvUdpSocket {
     // XXX implement
-    fn socket_name(&mut self) -> IpAddr { fail!(); }
+    fn socket_name(&mut self) -> SocketAddr { fail!(); }
 }
 
 impl RtioUdpSocket for UvUdpSocket {
-    fn recvfrom(&mut self, buf: &mut [u8]) -> Result<(uint, IpAddr), IoError> {
+    fn recvfrom(&mut self, buf: &mut [u8]) -> Result<(uint, SocketAddr), IoError> {
         let result_cell = Cell::new_empty();
-        let result_cell_ptr: *Cell<Result<(uint, IpAddr), IoError>> = &result_cell;
+        let result_cell_ptr: *Cell<Result<(uint, SocketAddr), IoError>> = &result_cell;
 
         let scheduler = Local::take::<Scheduler>();
         assert!(scheduler.in_task_context());
@@ -525,7 +754,7 @@ impl RtioUdpSocket for UvUdpSocket {
         return result_cell.take();
     }
 
-    fn sendto(&mut self, buf: &[u8], dst: IpAddr) -> Result<(), IoError> {
+    fn sendto(&mut self, buf: &[u8], dst: SocketAddr) -> Result<(), IoError> {
         let result_cell = Cell::new_empty();
         let result_cell_ptr: *Cell<Result<(), IoError>> = &result_cell;
         let scheduler = Local::take::<Scheduler>();
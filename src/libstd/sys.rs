@@ -175,6 +175,15 @@ pub fn begin_unwind_(msg: *c_char, file: *c_char, line: size_t) -> ! {
                 let outmsg = fmt!("task failed at '%s', %s:%i",
                                   msg, file, line as int);
 
+                let task = Local::unsafe_borrow::<Task>();
+                if (*task).unwinder.unwinding {
+                    rtabort!("unwinding again");
+                }
+                // Stash a copy before `outmsg` is consumed below, so
+                // anything running as part of the unwind (destructors,
+                // `Finally` blocks) can still learn what failed.
+                (*task).unwinder.fail_message = Some(outmsg.clone());
+
                 // XXX: Logging doesn't work correctly in non-task context because it
                 // invokes the local heap
                 if context == TaskContext {
@@ -190,10 +199,6 @@ pub fn begin_unwind_(msg: *c_char, file: *c_char, line: size_t) -> ! {
 
                 gc::cleanup_stack_for_failure();
 
-                let task = Local::unsafe_borrow::<Task>();
-                if (*task).unwinder.unwinding {
-                    rtabort!("unwinding again");
-                }
                 (*task).unwinder.begin_unwind();
             }
         }